@@ -8,8 +8,21 @@ use std::path::Path;
 pub struct LogRule {
     pub name: String,
     pub pattern: String, // Regex string from config
-    pub threshold: u64,  // Simple threshold (e.g. notify after X occurrences)
-                         // In a real app we might have time_window, etc.
+    pub threshold: u64,  // Notify after this many matches...
+    /// ...within this many seconds. A sliding window, not a lifetime count:
+    /// once `threshold` matches land inside `time_window_secs`, the window
+    /// is cleared and has to refill before the rule can fire again.
+    #[serde(default = "default_time_window_secs")]
+    pub time_window_secs: u64,
+    /// When true, a matching line is also fed to the `BanTracker`: the first
+    /// IPv4 address found in the line counts as one failure for that IP
+    /// (failures are counted per IP, across all rules).
+    #[serde(default)]
+    pub extract_ip: bool,
+}
+
+fn default_time_window_secs() -> u64 {
+    60
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -17,6 +30,106 @@ pub struct AppConfig {
     pub rules: Vec<LogRule>,
     pub polling_interval_ms: u64,
     pub webhook_url: Option<String>,
+    /// How long a deduplicated incident stays suppressed before we're willing
+    /// to re-alert on it.
+    #[serde(default = "default_dedup_window_secs")]
+    pub dedup_window_secs: u64,
+    /// How long an incident can go unseen before we consider it resolved and
+    /// emit a "Resolved" webhook for it.
+    #[serde(default = "default_resolve_after_secs")]
+    pub resolve_after_secs: u64,
+    /// Sliding-window threshold for the JSON-error fast path, mirroring
+    /// `LogRule::threshold`/`time_window_secs` for regex rules.
+    #[serde(default = "default_json_error_threshold")]
+    pub json_error_threshold: u64,
+    #[serde(default = "default_time_window_secs")]
+    pub json_error_time_window_secs: u64,
+    /// Opt-in fail2ban-style IP ban tracker. `None` disables it entirely.
+    #[serde(default)]
+    pub security: Option<SecurityConfig>,
+    /// Log files to tail. Defaults to a single `test.log` source for
+    /// backwards compatibility with the old hardcoded path.
+    #[serde(default = "default_sources")]
+    pub sources: Vec<SourceConfig>,
+    /// Opt-in JSON-RPC socket for external integrations. `None` disables it.
+    #[serde(default)]
+    pub rpc: Option<RpcConfig>,
+    /// `tracing` max level, e.g. `"info"`, `"debug"`, `"trace"`. Logs are
+    /// written to `sentinel.log`, not stdout, since the TUI owns the
+    /// terminal via the alternate screen.
+    #[serde(default = "default_log_level")]
+    pub log_level: String,
+    /// When true, log each completed webhook delivery (status code and
+    /// latency) rather than only failures.
+    #[serde(default = "default_log_completed_requests")]
+    pub log_completed_requests: bool,
+}
+
+fn default_log_level() -> String {
+    "info".to_string()
+}
+
+fn default_log_completed_requests() -> bool {
+    true
+}
+
+/// Where to expose the newline-delimited JSON-RPC endpoint. At least one of
+/// the two should be set; both can be set to listen on both at once.
+#[derive(Debug, Deserialize, Clone)]
+pub struct RpcConfig {
+    pub unix_socket_path: Option<String>,
+    pub tcp_addr: Option<String>,
+}
+
+fn default_sources() -> Vec<SourceConfig> {
+    vec![SourceConfig {
+        path: "test.log".to_string(),
+        label: "default".to_string(),
+    }]
+}
+
+fn default_json_error_threshold() -> u64 {
+    1
+}
+
+/// Settings for the fail2ban-style IP offender tracker. Absent from config,
+/// the whole subsystem is disabled: no IPs are extracted or banned.
+#[derive(Debug, Deserialize, Clone)]
+pub struct SecurityConfig {
+    /// Ban an IP once it has this many failures within `ban_window_secs`.
+    pub ban_threshold: u64,
+    pub ban_window_secs: u64,
+    /// How long a ban lasts before the IP is automatically removed.
+    pub ban_duration_secs: u64,
+    /// Newly-banned IPs are appended here, one per line.
+    pub blocklist_path: String,
+    /// Optional shell command template run on ban, with `{ip}` substituted
+    /// for the offending address (e.g. `"iptables -A INPUT -s {ip} -j DROP"`).
+    pub ban_command: Option<String>,
+    /// Optional shell command template run when a ban expires, with `{ip}`
+    /// substituted for the address (e.g. `"iptables -D INPUT -s {ip} -j
+    /// DROP"`). Without this, an expired ban is forgotten by Sentinel's own
+    /// bookkeeping but any firewall rule `ban_command` applied stays in
+    /// effect forever.
+    pub unban_command: Option<String>,
+}
+
+/// One log source to tail. `path` supports glob patterns (e.g.
+/// `/var/log/app/*.log`); every file the pattern expands to at startup
+/// becomes its own tracked source, labeled with `label` for display in the
+/// TUI (with the file path appended when the pattern matches several files).
+#[derive(Debug, Deserialize, Clone)]
+pub struct SourceConfig {
+    pub path: String,
+    pub label: String,
+}
+
+fn default_dedup_window_secs() -> u64 {
+    10
+}
+
+fn default_resolve_after_secs() -> u64 {
+    300
 }
 
 impl Default for AppConfig {
@@ -27,15 +140,28 @@ impl Default for AppConfig {
                     name: "Error".to_string(),
                     pattern: "(?i)error".to_string(),
                     threshold: 1,
+                    time_window_secs: default_time_window_secs(),
+                    extract_ip: false,
                 },
                 LogRule {
                     name: "Panic".to_string(),
                     pattern: "(?i)panic".to_string(),
                     threshold: 1,
+                    time_window_secs: default_time_window_secs(),
+                    extract_ip: false,
                 },
             ],
             polling_interval_ms: 100,
             webhook_url: None,
+            dedup_window_secs: default_dedup_window_secs(),
+            resolve_after_secs: default_resolve_after_secs(),
+            json_error_threshold: default_json_error_threshold(),
+            json_error_time_window_secs: default_time_window_secs(),
+            security: None,
+            sources: default_sources(),
+            rpc: None,
+            log_level: default_log_level(),
+            log_completed_requests: default_log_completed_requests(),
         }
     }
 }
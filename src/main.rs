@@ -1,9 +1,10 @@
 mod config;
 mod parser;
+mod rpc;
 mod state;
 
-use crate::config::load_config;
-use crate::parser::LogParser;
+use crate::config::{SourceConfig, load_config};
+use crate::parser::{LogParser, apply_unbans};
 use crate::state::AppState;
 use anyhow::Result;
 use crossterm::{
@@ -16,36 +17,180 @@ use ratatui::{
     backend::CrosstermBackend,
     layout::{Constraint, Direction, Layout},
     style::{Color, Style},
-    widgets::{Block, Borders, Paragraph, Sparkline},
+    widgets::{Block, Borders, List, ListItem, ListState, Paragraph, Sparkline},
 };
 use std::io;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
 use tokio::fs::File;
 use tokio::time::sleep;
 
+/// Route `tracing` output to `sentinel.log` instead of stdout: the TUI owns
+/// stdout via the alternate screen, so writing logs there would corrupt the
+/// display. `log_level` is whatever `AppConfig.log_level` resolves to,
+/// falling back to `INFO` if it doesn't parse as a `tracing::Level`.
+fn init_logging(log_level: &str) {
+    let level: tracing::Level = log_level.parse().unwrap_or(tracing::Level::INFO);
+    let log_file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open("sentinel.log")
+        .expect("failed to open sentinel.log for logging");
+
+    tracing_subscriber::fmt()
+        .with_max_level(level)
+        .with_ansi(false)
+        .with_writer(Mutex::new(log_file))
+        .init();
+}
+
+/// Expand each `SourceConfig.path` glob into the files it currently matches,
+/// pairing every resulting path with the display label it should use. A
+/// pattern with no glob metacharacters still works here: `glob` treats it as
+/// a literal path with a single match.
+fn expand_sources(configs: &[SourceConfig]) -> Vec<(String, String)> {
+    let mut expanded = Vec::new();
+
+    for cfg in configs {
+        let matches: Vec<_> = glob::glob(&cfg.path)
+            .map(|paths| paths.filter_map(Result::ok).collect())
+            .unwrap_or_default();
+
+        if matches.is_empty() {
+            // No files matched (yet). A literal (non-glob) path still gets
+            // tracked so it shows up immediately and can be created below;
+            // a true glob pattern is skipped — treating the pattern string
+            // itself as a path would create a bogus literal file (e.g.
+            // `*.log`) that then matches the same glob forever. Sources are
+            // expanded only once, at startup, so files that match the
+            // pattern later are not tailed.
+            if is_glob_pattern(&cfg.path) {
+                continue;
+            }
+            expanded.push((cfg.path.clone(), cfg.label.clone()));
+            continue;
+        }
+
+        let label_one_per_match = matches.len() > 1;
+        for path in matches {
+            let id = path.to_string_lossy().to_string();
+            let label = if label_one_per_match {
+                format!("{} [{}]", cfg.label, path.display())
+            } else {
+                cfg.label.clone()
+            };
+            expanded.push((id, label));
+        }
+    }
+
+    expanded
+}
+
+/// Whether `path` contains glob metacharacters, as opposed to being a
+/// literal filesystem path that just happens to not exist yet.
+fn is_glob_pattern(path: &str) -> bool {
+    path.contains(['*', '?', '[', ']'])
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
     // 1. Setup
     let args: Vec<String> = std::env::args().collect();
     let is_simulator = args.contains(&"--simulate".to_string());
-    let log_path = "test.log";
 
     let config = load_config("config.yaml")?;
-    let state = Arc::new(AppState::new(config.webhook_url.clone()));
-    let parser = Arc::new(LogParser::new(&config.rules));
+    init_logging(&config.log_level);
+    let sources = expand_sources(&config.sources);
+
+    let state = Arc::new(AppState::with_sources(
+        sources.clone(),
+        config.webhook_url.clone(),
+        config.dedup_window_secs,
+        config.resolve_after_secs,
+        config.security.as_ref(),
+    ));
+    let parser = Arc::new(LogParser::with_json_error_window(
+        &config.rules,
+        config.json_error_threshold,
+        config.json_error_time_window_secs,
+    ));
+
+    if let Some(rpc_config) = config.rpc.as_ref() {
+        rpc::serve(rpc_config, state.clone()).await;
+    }
+
+    // Single dispatcher task for every queued webhook POST, sharing one
+    // `reqwest::Client` instead of spinning up a task-plus-client per alert.
+    {
+        let dispatch_state = state.clone();
+        let log_completed_requests = config.log_completed_requests;
+        tokio::spawn(async move {
+            loop {
+                let job = dispatch_state.next_webhook_job().await;
+                if let Some(url) = dispatch_state.webhook_url.clone() {
+                    let payload = serde_json::json!({ "text": job.text });
+                    let started = std::time::Instant::now();
+                    let result = dispatch_state
+                        .http_client
+                        .post(&url)
+                        .json(&payload)
+                        .send()
+                        .await;
+                    let latency_ms = started.elapsed().as_millis();
+
+                    match result {
+                        Ok(response) if log_completed_requests => {
+                            tracing::info!(
+                                status = %response.status(),
+                                latency_ms,
+                                "webhook delivered"
+                            );
+                        }
+                        Ok(_) => {}
+                        Err(e) => {
+                            tracing::warn!(error = %e, latency_ms, "webhook delivery failed");
+                        }
+                    }
+                }
+            }
+        });
+    }
+
+    // Background sweeper: closes out incidents that have gone quiet by
+    // emitting a "Resolved" webhook and dropping them from the dedup map,
+    // and expires bans whose duration has elapsed.
+    let resolver_state = state.clone();
+    tokio::spawn(async move {
+        loop {
+            sleep(Duration::from_secs(5)).await;
+            for (_, message) in resolver_state.sweep_resolved_incidents() {
+                if resolver_state.webhook_url.is_some() {
+                    resolver_state.send_webhook(format!("✅ Sentinel Resolved: {}", message));
+                }
+            }
+
+            if let Some(ban_tracker) = resolver_state.ban_tracker.as_ref() {
+                let expired = ban_tracker.sweep_expired();
+                apply_unbans(ban_tracker, expired);
+            }
+        }
+    });
 
     // 2. Spawn Log Processor
     let state_clone = state.clone();
     let parser_clone = parser.clone();
-    let path_clone = log_path.to_string();
+    let source_paths: Vec<String> = sources.iter().map(|(id, _)| id.clone()).collect();
 
     // START SIMULATOR IF REQUESTED
     if is_simulator {
+        let sim_path = source_paths
+            .first()
+            .cloned()
+            .unwrap_or_else(|| "test.log".to_string());
         tokio::spawn(async move {
             // Internal generator logic
             use tokio::io::AsyncWriteExt;
-            let mut file = File::create(&path_clone).await.unwrap();
+            let mut file = File::create(&sim_path).await.unwrap();
             let mut counter = 0;
             loop {
                 counter += 1;
@@ -69,24 +214,37 @@ async fn main() -> Result<()> {
             }
         });
     } else {
-        // Ensure file exists for linemux if not simulating (linemux might error if missing)
-        if tokio::fs::metadata(log_path).await.is_err() {
-            File::create(log_path).await?;
+        // Ensure every source file exists for linemux (it errors if missing)
+        for path in &source_paths {
+            if tokio::fs::metadata(path).await.is_err() {
+                File::create(path).await?;
+            }
         }
     }
 
     // TAIL LOGIC (Linemux)
     tokio::spawn(async move {
-        // We use linemux to handle rotation and standardized tailing
+        // We use linemux to handle rotation and standardized tailing across
+        // every configured source.
         let mut lines = linemux::MuxedLines::new().expect("Could not initialize linemux");
-        lines
-            .add_file(log_path)
-            .await
-            .expect("Failed to add file to tail");
+        for path in &source_paths {
+            match lines.add_file(path).await {
+                Ok(_) => tracing::info!(path = %path, "tailing source"),
+                Err(e) => {
+                    // Skip just this source and keep tailing the rest — a
+                    // glob can expand into many sources, so one bad path
+                    // shouldn't take down every other one.
+                    tracing::error!(path = %path, error = %e, "failed to add file to tail, skipping");
+                }
+            }
+        }
 
         while let Ok(Some(line)) = lines.next_line().await {
-            parser_clone.process_line(line.line(), &state_clone);
+            let source_id = line.source().to_string_lossy().to_string();
+            parser_clone.process_line(line.line(), &source_id, &state_clone);
         }
+
+        tracing::warn!("linemux tailer loop exited, no more sources are being watched");
     });
 
     // 3. TUI (The "Consumer")
@@ -119,96 +277,168 @@ fn run_app(
     terminal: &mut Terminal<CrosstermBackend<std::io::Stdout>>,
     state: Arc<AppState>,
 ) -> io::Result<()> {
-    // Local history buffer for Sparkline (UI Thread Only)
-    let mut error_history: Vec<u64> = vec![0; 100];
-    let mut last_total_errors = state
-        .total_errors
-        .load(std::sync::atomic::Ordering::Relaxed);
+    // Stable, sorted list of source ids so the selectable list doesn't
+    // reorder itself between frames (HashMap iteration order isn't stable).
+    let mut source_ids: Vec<String> = state.sources.keys().cloned().collect();
+    source_ids.sort();
+
+    let mut selected = 0usize;
+    let mut list_state = ListState::default();
+    list_state.select(Some(selected));
+
+    // Per-source sparkline history (UI Thread Only)
+    let mut error_history: Vec<Vec<u64>> = source_ids.iter().map(|_| vec![0u64; 100]).collect();
+    let mut last_total_errors: Vec<u64> = source_ids
+        .iter()
+        .map(|id| {
+            state.sources[id]
+                .total_errors
+                .load(std::sync::atomic::Ordering::Relaxed)
+        })
+        .collect();
     let mut last_update = std::time::Instant::now();
 
     loop {
         terminal.draw(|f| {
+            let outer = Layout::default()
+                .direction(Direction::Horizontal)
+                .margin(1)
+                .constraints([Constraint::Percentage(25), Constraint::Percentage(75)].as_ref())
+                .split(f.area());
+
+            // 0. Source list
+            let items: Vec<ListItem> = source_ids
+                .iter()
+                .map(|id| ListItem::new(state.sources[id].label.clone()))
+                .collect();
+            let list = List::new(items)
+                .block(Block::default().title("Sources").borders(Borders::ALL))
+                .highlight_style(Style::default().fg(Color::Black).bg(Color::Cyan));
+            f.render_stateful_widget(list, outer[0], &mut list_state);
+
             let chunks = Layout::default()
                 .direction(Direction::Vertical)
-                .margin(1)
                 .constraints(
                     [
-                        Constraint::Percentage(30),
-                        Constraint::Percentage(40),
-                        Constraint::Percentage(30),
+                        Constraint::Percentage(25),
+                        Constraint::Percentage(35),
+                        Constraint::Percentage(20),
+                        Constraint::Percentage(20),
                     ]
                     .as_ref(),
                 )
-                .split(f.area());
+                .split(outer[1]);
 
-            // Update buckets for time progression (UI Side)
+            // Update per-source error-rate buckets once a second.
             if last_update.elapsed() >= Duration::from_millis(1000) {
-                let current_total = state
-                    .total_errors
-                    .load(std::sync::atomic::Ordering::Relaxed);
-                let delta = current_total.saturating_sub(last_total_errors);
-
-                error_history.remove(0);
-                error_history.push(delta);
+                for (i, id) in source_ids.iter().enumerate() {
+                    let current_total = state.sources[id]
+                        .total_errors
+                        .load(std::sync::atomic::Ordering::Relaxed);
+                    let delta = current_total.saturating_sub(last_total_errors[i]);
 
-                last_total_errors = current_total;
+                    error_history[i].remove(0);
+                    error_history[i].push(delta);
+                    last_total_errors[i] = current_total;
+                }
                 last_update = std::time::Instant::now();
             }
 
-            // 1. Stats Block
-            let total_lines = state.total_lines.load(std::sync::atomic::Ordering::Relaxed);
-            let total_errors = state
-                .total_errors
-                .load(std::sync::atomic::Ordering::Relaxed);
             let elapsed = state.start_time.elapsed().as_secs();
-            let rate = if elapsed > 0 {
-                total_lines / elapsed
-            } else {
-                0
-            };
 
-            let stats_text = format!(
-                "Lines Processed: {}\nErrors Found: {}\nTime Elapsed: {}s\nRate: {} lines/s",
-                total_lines, total_errors, elapsed, rate
-            );
-
-            let stats_paragraph = Paragraph::new(stats_text).block(
-                Block::default()
-                    .title("Sentinel Status")
-                    .borders(Borders::ALL),
-            );
-            f.render_widget(stats_paragraph, chunks[0]);
-
-            // 2. Visual "Sparkline" (Error Rate History)
-            let sparkline = Sparkline::default()
-                .block(
+            if let Some(id) = source_ids.get(selected) {
+                let stats = &state.sources[id];
+
+                // 1. Stats Block (selected source)
+                let total_lines = stats.total_lines.load(std::sync::atomic::Ordering::Relaxed);
+                let total_errors = stats
+                    .total_errors
+                    .load(std::sync::atomic::Ordering::Relaxed);
+                let rate = if elapsed > 0 {
+                    total_lines / elapsed
+                } else {
+                    0
+                };
+
+                let stats_text = format!(
+                    "Source: {}\nLines Processed: {}\nErrors Found: {}\nTime Elapsed: {}s\nRate: {} lines/s",
+                    stats.label, total_lines, total_errors, elapsed, rate
+                );
+
+                let stats_paragraph = Paragraph::new(stats_text).block(
                     Block::default()
-                        .title("Error Rate (Last 100s)")
+                        .title("Sentinel Status")
                         .borders(Borders::ALL),
-                )
-                .data(&error_history)
-                .style(Style::default().fg(Color::Red));
-            f.render_widget(sparkline, chunks[1]);
-
-            // 3. Last Alert
-            let last_err = state.last_error.lock().unwrap();
-            let alert_text = last_err
-                .clone()
-                .unwrap_or_else(|| "No errors yet.".to_string());
-            let alert_widget = Paragraph::new(alert_text)
-                .style(Style::default().fg(if last_err.is_some() {
-                    Color::Red
+                );
+                f.render_widget(stats_paragraph, chunks[0]);
+
+                // 2. Visual "Sparkline" (Error Rate History)
+                let sparkline = Sparkline::default()
+                    .block(
+                        Block::default()
+                            .title("Error Rate (Last 100s)")
+                            .borders(Borders::ALL),
+                    )
+                    .data(&error_history[selected])
+                    .style(Style::default().fg(Color::Red));
+                f.render_widget(sparkline, chunks[1]);
+
+                // 3. Recent Alerts (bounded ring, newest first). The panel does
+                // not scroll: entries beyond its height are not shown.
+                let alerts = stats.recent_alerts_snapshot();
+                let alert_items: Vec<ListItem> = if alerts.is_empty() {
+                    vec![ListItem::new("No errors yet.").style(Style::default().fg(Color::Gray))]
                 } else {
-                    Color::Gray
-                }))
-                .block(Block::default().title("Last Alert").borders(Borders::ALL));
-            f.render_widget(alert_widget, chunks[2]);
+                    alerts
+                        .iter()
+                        .rev()
+                        .map(|a| ListItem::new(a.clone()).style(Style::default().fg(Color::Red)))
+                        .collect()
+                };
+                let alert_widget = List::new(alert_items).block(
+                    Block::default()
+                        .title("Recent Alerts")
+                        .borders(Borders::ALL),
+                );
+                f.render_widget(alert_widget, chunks[2]);
+            }
+
+            // 4. Banned IPs (fail2ban-style offender tracking, global across sources)
+            let banned_text = match state.ban_tracker.as_ref() {
+                Some(tracker) => {
+                    let mut ips = tracker.banned_ips();
+                    if ips.is_empty() {
+                        "No banned IPs.".to_string()
+                    } else {
+                        ips.sort();
+                        ips.join("\n")
+                    }
+                }
+                None => "Security subsystem disabled.".to_string(),
+            };
+            let banned_widget = Paragraph::new(banned_text)
+                .style(Style::default().fg(Color::Yellow))
+                .block(Block::default().title("Banned IPs").borders(Borders::ALL));
+            f.render_widget(banned_widget, chunks[3]);
         })?;
 
         if event::poll(Duration::from_millis(100))? {
             if let Event::Key(key) = event::read()? {
-                if key.code == KeyCode::Char('q') {
-                    return Ok(());
+                match key.code {
+                    KeyCode::Char('q') => return Ok(()),
+                    KeyCode::Up | KeyCode::Char('k') => {
+                        if selected > 0 {
+                            selected -= 1;
+                            list_state.select(Some(selected));
+                        }
+                    }
+                    KeyCode::Down | KeyCode::Char('j') => {
+                        if selected + 1 < source_ids.len() {
+                            selected += 1;
+                            list_state.select(Some(selected));
+                        }
+                    }
+                    _ => {}
                 }
             }
         }
@@ -1,8 +1,10 @@
 use crate::config::LogRule;
-use crate::state::AppState;
-use regex::RegexSet;
+use crate::state::{AlertDecision, AppState, BanTracker, fingerprint_message};
+use regex::{Regex, RegexSet};
 use serde::Deserialize;
-use std::sync::Arc;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 #[derive(Deserialize)]
 struct PartialLog {
@@ -12,68 +14,170 @@ struct PartialLog {
     message: Option<String>,
 }
 
+/// A per-rule sliding window of recent match timestamps, used to implement
+/// `LogRule::threshold`/`time_window_secs`.
+struct MatchWindow {
+    threshold: u64,
+    time_window_secs: u64,
+    timestamps: Mutex<VecDeque<Instant>>,
+}
+
+impl MatchWindow {
+    fn new(threshold: u64, time_window_secs: u64) -> Self {
+        Self {
+            threshold,
+            time_window_secs,
+            timestamps: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /// Record a match now, evict stale entries outside the window, and
+    /// report whether the window has reached `threshold`. Reaching it clears
+    /// the window so it must refill before firing again.
+    fn record(&self) -> bool {
+        let mut timestamps = self.timestamps.lock().unwrap();
+        let now = Instant::now();
+        let cutoff = Duration::from_secs(self.time_window_secs);
+
+        timestamps.push_back(now);
+        while let Some(&front) = timestamps.front() {
+            if now.duration_since(front) > cutoff {
+                timestamps.pop_front();
+            } else {
+                break;
+            }
+        }
+
+        if timestamps.len() as u64 >= self.threshold {
+            timestamps.clear();
+            true
+        } else {
+            false
+        }
+    }
+}
+
 pub struct LogParser {
     // Optimization: RegexSet for checking ALL patterns in one pass
     regex_set: RegexSet,
     // Names correspond to indices in regex_set
     rule_names: Vec<String>,
+    // Indexed parallel to rule_names / regex_set.
+    rule_windows: Vec<MatchWindow>,
+    // Indexed parallel to rule_names / regex_set; true for rules that should
+    // feed matched IPs into the BanTracker.
+    extract_ip_flags: Vec<bool>,
+    json_error_window: MatchWindow,
+    // Generic IPv4 extractor, shared by every `extract_ip` rule.
+    ip_regex: Regex,
 }
 
 impl LogParser {
     pub fn new(config_rules: &[LogRule]) -> Self {
-        // Extract patterns strings
-        let patterns: Vec<String> = config_rules.iter().map(|r| r.pattern.clone()).collect();
+        Self::with_json_error_window(config_rules, 1, 60)
+    }
+
+    pub fn with_json_error_window(
+        config_rules: &[LogRule],
+        json_error_threshold: u64,
+        json_error_time_window_secs: u64,
+    ) -> Self {
+        // Validate each pattern individually first so one bad rule just gets
+        // logged and skipped instead of taking down the whole `RegexSet`
+        // (and the process, since this used to be a bare `.expect()`).
+        let mut patterns = Vec::new();
+        let mut rule_names = Vec::new();
+        let mut rule_windows = Vec::new();
+        let mut extract_ip_flags = Vec::new();
 
-        let rule_names = config_rules.iter().map(|r| r.name.clone()).collect();
+        for (index, rule) in config_rules.iter().enumerate() {
+            if let Err(e) = Regex::new(&rule.pattern) {
+                tracing::error!(
+                    rule_index = index,
+                    pattern = %rule.pattern,
+                    error = %e,
+                    "rule pattern failed to compile, skipping"
+                );
+                continue;
+            }
 
-        let regex_set = RegexSet::new(&patterns).expect("Invalid RegexSet in config");
+            patterns.push(rule.pattern.clone());
+            rule_names.push(rule.name.clone());
+            rule_windows.push(MatchWindow::new(rule.threshold, rule.time_window_secs));
+            extract_ip_flags.push(rule.extract_ip);
+        }
+
+        let regex_set =
+            RegexSet::new(&patterns).expect("patterns were already validated individually");
+        let ip_regex = Regex::new(r"\b(?:\d{1,3}\.){3}\d{1,3}\b").expect("Invalid IP regex");
 
         Self {
             regex_set,
             rule_names,
+            rule_windows,
+            extract_ip_flags,
+            json_error_window: MatchWindow::new(json_error_threshold, json_error_time_window_secs),
+            ip_regex,
         }
     }
 
-    pub fn process_line(&self, line: &str, state: &Arc<AppState>) {
-        state.increment_lines();
+    pub fn process_line(&self, line: &str, source_id: &str, state: &Arc<AppState>) {
+        state.increment_lines(source_id);
 
         // 1. Intelligent JSON Parsing (Star Material - Zero Lag Optimized)
         if line.trim_start().starts_with('{') {
             // Using PartialLog struct is faster than parsing into a Value map
-            if let Ok(json) = serde_json::from_str::<PartialLog>(line) {
-                let level_str = json.level.as_deref().or(json.severity.as_deref());
-
-                let is_error = level_str
-                    .map(|s: &str| {
-                        let s = s.to_lowercase();
-                        s == "error" || s == "panic" || s == "fatal"
-                    })
-                    .unwrap_or(false);
-
-                if is_error {
-                    // Try to format a nice message: "JSON Error: <msg>"
-                    let msg = json
-                        .message
-                        .as_deref()
-                        .or(json.msg.as_deref())
-                        .unwrap_or(line);
-
-                    state.record_error(format!("JSON: {}", msg));
-
-                    // Webhook Trigger (Rate Limited)
-                    if state.webhook_url.is_some() && state.should_send_webhook() {
-                        let url = state.webhook_url.clone().unwrap();
-                        let msg = msg.to_string();
-                        tokio::spawn(async move {
-                            let client = reqwest::Client::new();
-                            let payload = serde_json::json!({
-                                "text": format!("🚨 Sentinel Alert: JSON Error Detected!\nMessage: {}", msg)
-                            });
-                            let _ = client.post(&url).json(&payload).send().await;
-                        });
-                    }
+            match serde_json::from_str::<PartialLog>(line) {
+                Ok(json) => {
+                    let level_str = json.level.as_deref().or(json.severity.as_deref());
+
+                    let is_error = level_str
+                        .map(|s: &str| {
+                            let s = s.to_lowercase();
+                            s == "error" || s == "panic" || s == "fatal"
+                        })
+                        .unwrap_or(false);
+
+                    if is_error {
+                        // Try to format a nice message: "JSON: <msg>"
+                        let msg = json
+                            .message
+                            .as_deref()
+                            .or(json.msg.as_deref())
+                            .unwrap_or(line);
+
+                        // Only count this towards an incident once the sliding
+                        // window has seen `threshold` matches.
+                        if self.json_error_window.record() {
+                            let full_message = format!("JSON: {}", msg);
+                            state.record_error(source_id, full_message.clone());
 
-                    return; // Early exit if JSON caught it
+                            // Webhook Trigger (Deduplicated)
+                            if state.webhook_url.is_some() {
+                                let fingerprint = fingerprint_message(&full_message);
+                                if let AlertDecision::Send {
+                                    suppressed_since_last,
+                                } = state.record_alert(fingerprint, &full_message)
+                                {
+                                    queue_webhook_alert(
+                                        state,
+                                        "JSON Error Detected!",
+                                        &full_message,
+                                        suppressed_since_last,
+                                    );
+                                }
+                            }
+                        }
+
+                        return; // Early exit if JSON caught it
+                    }
+                }
+                Err(e) => {
+                    tracing::trace!(
+                        source_id = %source_id,
+                        error = %e,
+                        "line looked like JSON but failed to parse, falling back to regex"
+                    );
                 }
             }
         }
@@ -84,6 +188,23 @@ impl LogParser {
             let matches: Vec<_> = self.regex_set.matches(line).into_iter().collect();
 
             if !matches.is_empty() {
+                // Security subsystem: feed the matched IP into the ban
+                // tracker if ANY matched rule asks for it, independent of
+                // which rule we pick below for the error/panic alert — a
+                // line can match an `extract_ip` security rule at one index
+                // and a non-`extract_ip` error rule at another, and the ban
+                // tracker must still see it regardless of rule ordering.
+                if matches.iter().any(|&idx| self.extract_ip_flags[idx]) {
+                    if let (Some(ip_match), Some(ban_tracker)) =
+                        (self.ip_regex.find(line), state.ban_tracker.as_ref())
+                    {
+                        let ip = ip_match.as_str().to_string();
+                        if ban_tracker.record_failure(&ip) {
+                            apply_ban(ban_tracker, ip);
+                        }
+                    }
+                }
+
                 // Just grab the name of the first match
                 // In a real app we might handle multiple matches
                 let idx = matches[0];
@@ -93,19 +214,26 @@ impl LogParser {
                 if rule_name.to_lowercase().contains("error")
                     || rule_name.to_lowercase().contains("panic")
                 {
-                    state.record_error(line.to_string());
-
-                    // Webhook Trigger (Rate Limited)
-                    if state.webhook_url.is_some() && state.should_send_webhook() {
-                        let url = state.webhook_url.clone().unwrap();
-                        let line = line.to_string();
-                        tokio::spawn(async move {
-                            let client = reqwest::Client::new();
-                            let payload = serde_json::json!({
-                                "text": format!("🚨 Sentinel Alert: Pattern Match!\nLog: {}", line)
-                            });
-                            let _ = client.post(&url).json(&payload).send().await;
-                        });
+                    // Only count this towards an incident once the rule's
+                    // sliding window has seen `threshold` matches.
+                    if self.rule_windows[idx].record() {
+                        state.record_error(source_id, line.to_string());
+
+                        // Webhook Trigger (Deduplicated)
+                        if state.webhook_url.is_some() {
+                            let fingerprint = fingerprint_message(line);
+                            if let AlertDecision::Send {
+                                suppressed_since_last,
+                            } = state.record_alert(fingerprint, line)
+                            {
+                                queue_webhook_alert(
+                                    state,
+                                    "Pattern Match!",
+                                    line,
+                                    suppressed_since_last,
+                                );
+                            }
+                        }
                     }
                 }
             }
@@ -113,11 +241,89 @@ impl LogParser {
     }
 }
 
+/// Queue a webhook POST for a newly-sent (i.e. not suppressed) alert onto
+/// `state`'s dispatch queue, where the single dispatcher task (spawned in
+/// `main`) sends it using the shared `http_client`. `suppressed_since_last`
+/// is folded into the text so the recipient can see how much noise this
+/// incident generated while it was being deduplicated.
+fn queue_webhook_alert(state: &Arc<AppState>, title: &str, body: &str, suppressed_since_last: u64) {
+    let suffix = if suppressed_since_last > 0 {
+        format!(" (+{} more since last alert)", suppressed_since_last)
+    } else {
+        String::new()
+    };
+    state.send_webhook(format!("🚨 Sentinel Alert: {}\n{}{}", title, body, suffix));
+}
+
+/// Apply a newly-triggered ban: append the IP to the blocklist file and, if
+/// configured, run the ban command template with `{ip}` substituted.
+fn apply_ban(tracker: &BanTracker, ip: String) {
+    let blocklist_path = tracker.blocklist_path.clone();
+    let ban_command = tracker.ban_command.clone();
+
+    tokio::spawn(async move {
+        use tokio::io::AsyncWriteExt;
+
+        if let Ok(mut file) = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&blocklist_path)
+            .await
+        {
+            let _ = file.write_all(format!("{}\n", ip).as_bytes()).await;
+        }
+
+        if let Some(template) = ban_command {
+            let command = template.replace("{ip}", &ip);
+            let _ = tokio::process::Command::new("sh")
+                .arg("-c")
+                .arg(command)
+                .status()
+                .await;
+        }
+    });
+}
+
+/// Reverse bans for IPs `BanTracker::sweep_expired` just dropped from its own
+/// bookkeeping: strip their lines out of `blocklist_path` and, if
+/// `unban_command` is configured, run it once per IP. Without this, an
+/// expired ban stays enforced forever by whatever `ban_command` applied
+/// (e.g. an `iptables` rule), and the blocklist file only ever grows.
+pub fn apply_unbans(tracker: &BanTracker, ips: Vec<String>) {
+    if ips.is_empty() {
+        return;
+    }
+
+    let blocklist_path = tracker.blocklist_path.clone();
+    let unban_command = tracker.unban_command.clone();
+
+    tokio::spawn(async move {
+        if let Ok(contents) = tokio::fs::read_to_string(&blocklist_path).await {
+            let filtered: String = contents
+                .lines()
+                .filter(|line| !ips.iter().any(|ip| ip == line))
+                .map(|line| format!("{}\n", line))
+                .collect();
+            let _ = tokio::fs::write(&blocklist_path, filtered).await;
+        }
+
+        if let Some(template) = unban_command {
+            for ip in &ips {
+                let command = template.replace("{ip}", ip);
+                let _ = tokio::process::Command::new("sh")
+                    .arg("-c")
+                    .arg(command)
+                    .status()
+                    .await;
+            }
+        }
+    });
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::state::AppState;
-    use std::sync::atomic::Ordering;
+    use crate::state::{AppState, DEFAULT_SOURCE_ID};
 
     #[test]
     fn test_process_line_regex_match() {
@@ -125,14 +331,16 @@ mod tests {
             name: "TestError".to_string(),
             pattern: "panic!".to_string(),
             threshold: 1,
+            time_window_secs: 60,
+            extract_ip: false,
         }];
         let parser = LogParser::new(&rules);
         let state = Arc::new(AppState::new(None));
 
-        parser.process_line("System panic! at the disco", &state);
+        parser.process_line("System panic! at the disco", DEFAULT_SOURCE_ID, &state);
 
-        assert_eq!(state.total_errors.load(Ordering::Relaxed), 1);
-        assert_eq!(state.total_lines.load(Ordering::Relaxed), 1);
+        assert_eq!(state.total_errors(), 1);
+        assert_eq!(state.total_lines(), 1);
     }
 
     #[test]
@@ -142,9 +350,9 @@ mod tests {
         let state = Arc::new(AppState::new(None));
 
         let json_log = r#"{"level": "error", "msg": "Database failed"}"#;
-        parser.process_line(json_log, &state);
+        parser.process_line(json_log, DEFAULT_SOURCE_ID, &state);
 
-        assert_eq!(state.total_errors.load(Ordering::Relaxed), 1);
+        assert_eq!(state.total_errors(), 1);
     }
 
     #[test]
@@ -153,13 +361,67 @@ mod tests {
             name: "TestError".to_string(),
             pattern: "panic!".to_string(),
             threshold: 1,
+            time_window_secs: 60,
+            extract_ip: false,
+        }];
+        let parser = LogParser::new(&rules);
+        let state = Arc::new(AppState::new(None));
+
+        parser.process_line("Just a normal info log", DEFAULT_SOURCE_ID, &state);
+
+        assert_eq!(state.total_errors(), 0);
+        assert_eq!(state.total_lines(), 1);
+    }
+
+    #[test]
+    fn test_process_line_respects_threshold() {
+        let rules = vec![LogRule {
+            name: "TestError".to_string(),
+            pattern: "panic!".to_string(),
+            threshold: 3,
+            time_window_secs: 60,
+            extract_ip: false,
         }];
         let parser = LogParser::new(&rules);
         let state = Arc::new(AppState::new(None));
 
-        parser.process_line("Just a normal info log", &state);
-        
-        assert_eq!(state.total_errors.load(Ordering::Relaxed), 0);
-        assert_eq!(state.total_lines.load(Ordering::Relaxed), 1);
+        parser.process_line("System panic! #1", DEFAULT_SOURCE_ID, &state);
+        parser.process_line("System panic! #2", DEFAULT_SOURCE_ID, &state);
+        assert_eq!(state.total_errors(), 0);
+
+        parser.process_line("System panic! #3", DEFAULT_SOURCE_ID, &state);
+        assert_eq!(state.total_errors(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_process_line_bans_ip_after_threshold() {
+        use crate::config::SecurityConfig;
+
+        let rules = vec![LogRule {
+            name: "FailedLogin".to_string(),
+            pattern: "Failed login".to_string(),
+            threshold: 1,
+            time_window_secs: 60,
+            extract_ip: true,
+        }];
+        let parser = LogParser::new(&rules);
+        let security = SecurityConfig {
+            ban_threshold: 2,
+            ban_window_secs: 60,
+            ban_duration_secs: 300,
+            blocklist_path: "/tmp/sentinel-test-blocklist.txt".to_string(),
+            ban_command: None,
+            unban_command: None,
+        };
+        let state = Arc::new(AppState::with_security(None, 10, 300, Some(&security)));
+
+        parser.process_line("Failed login from 10.0.0.1", DEFAULT_SOURCE_ID, &state);
+        assert!(state.ban_tracker.as_ref().unwrap().banned_ips().is_empty());
+
+        parser.process_line("Failed login from 10.0.0.1", DEFAULT_SOURCE_ID, &state);
+        assert_eq!(
+            state.ban_tracker.as_ref().unwrap().banned_ips(),
+            vec!["10.0.0.1".to_string()]
+        );
     }
 }
@@ -0,0 +1,279 @@
+//! Newline-delimited JSON-RPC server for external integrations. Disabled
+//! unless `AppConfig.rpc` is set; `main.rs` only calls [`serve`] when it is.
+//!
+//! Each line in is one request `{"id": ..., "method": ..., "params": ...}`,
+//! each line out is either a matching `{"id": ..., "result" | "error": ...}`
+//! response or, after `subscribe`, an unsolicited `{"method": "alert", ...}`
+//! notification for every subsequent `AppState::record_error` call.
+
+use crate::config::RpcConfig;
+use crate::state::{AlertEvent, AppState};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::sync::atomic::Ordering;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, UnixListener};
+use tokio::sync::broadcast;
+
+#[derive(Debug, Deserialize)]
+struct RpcRequest {
+    id: serde_json::Value,
+    method: String,
+}
+
+#[derive(Debug, Serialize)]
+struct RpcResponse {
+    id: serde_json::Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    result: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// Bind whichever of `unix_socket_path` / `tcp_addr` are configured and
+/// spawn an accept loop for each, handing every connection off to
+/// [`handle_connection`] on its own task. Bind failures are logged and
+/// otherwise non-fatal — the rest of Sentinel keeps running without RPC.
+pub async fn serve(config: &RpcConfig, state: Arc<AppState>) {
+    if let Some(path) = &config.unix_socket_path {
+        // Stale socket file from a previous run would otherwise make bind
+        // fail with "address in use".
+        let _ = std::fs::remove_file(path);
+        match UnixListener::bind(path) {
+            Ok(listener) => {
+                let state = state.clone();
+                tokio::spawn(async move {
+                    loop {
+                        if let Ok((stream, _)) = listener.accept().await {
+                            tokio::spawn(handle_connection(stream, state.clone()));
+                        }
+                    }
+                });
+            }
+            Err(e) => eprintln!("rpc: failed to bind unix socket {path}: {e}"),
+        }
+    }
+
+    if let Some(addr) = &config.tcp_addr {
+        match TcpListener::bind(addr).await {
+            Ok(listener) => {
+                let state = state.clone();
+                tokio::spawn(async move {
+                    loop {
+                        if let Ok((stream, _)) = listener.accept().await {
+                            tokio::spawn(handle_connection(stream, state.clone()));
+                        }
+                    }
+                });
+            }
+            Err(e) => eprintln!("rpc: failed to bind tcp {addr}: {e}"),
+        }
+    }
+}
+
+/// Drive one client connection: dispatch `getStats`/`getRecentAlerts`
+/// requests as they arrive, and once `subscribe` is called, also forward
+/// every `AlertEvent` broadcast by `state.alert_tx` as a notification.
+/// Generic over the stream so tests can drive it with an in-memory duplex
+/// pipe instead of a real socket.
+pub async fn handle_connection<S>(stream: S, state: Arc<AppState>)
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let (read_half, mut write_half) = tokio::io::split(stream);
+    let mut lines = BufReader::new(read_half).lines();
+    let mut alert_rx: Option<broadcast::Receiver<AlertEvent>> = None;
+
+    loop {
+        tokio::select! {
+            line = lines.next_line() => {
+                let line = match line {
+                    Ok(Some(line)) if !line.trim().is_empty() => line,
+                    Ok(Some(_)) => continue,
+                    _ => break,
+                };
+
+                let request: RpcRequest = match serde_json::from_str(&line) {
+                    Ok(request) => request,
+                    Err(e) => {
+                        let response = RpcResponse {
+                            id: serde_json::Value::Null,
+                            result: None,
+                            error: Some(format!("invalid request: {e}")),
+                        };
+                        if write_line(&mut write_half, &response).await.is_err() {
+                            break;
+                        }
+                        continue;
+                    }
+                };
+
+                let response = match request.method.as_str() {
+                    "getStats" => RpcResponse {
+                        id: request.id,
+                        result: Some(dispatch_get_stats(&state)),
+                        error: None,
+                    },
+                    "getRecentAlerts" => RpcResponse {
+                        id: request.id,
+                        result: Some(dispatch_get_recent_alerts(&state)),
+                        error: None,
+                    },
+                    "subscribe" => {
+                        alert_rx = Some(state.alert_tx.subscribe());
+                        RpcResponse {
+                            id: request.id,
+                            result: Some(serde_json::json!("subscribed")),
+                            error: None,
+                        }
+                    }
+                    other => RpcResponse {
+                        id: request.id,
+                        result: None,
+                        error: Some(format!("unknown method: {other}")),
+                    },
+                };
+
+                if write_line(&mut write_half, &response).await.is_err() {
+                    break;
+                }
+            }
+            event = recv_alert(&mut alert_rx) => {
+                let Some(event) = event else { continue };
+                let notification = serde_json::json!({"method": "alert", "params": event});
+                if write_line(&mut write_half, &notification).await.is_err() {
+                    break;
+                }
+            }
+        }
+    }
+}
+
+/// Await the next alert once subscribed; stays pending forever beforehand so
+/// the `tokio::select!` arm simply never fires for non-subscribed clients.
+async fn recv_alert(rx: &mut Option<broadcast::Receiver<AlertEvent>>) -> Option<AlertEvent> {
+    match rx {
+        Some(rx) => loop {
+            match rx.recv().await {
+                Ok(event) => return Some(event),
+                // We fell behind the ring buffer; skip to the next live event
+                // rather than erroring the connection out.
+                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        },
+        None => std::future::pending().await,
+    }
+}
+
+async fn write_line<W: AsyncWrite + Unpin>(
+    writer: &mut W,
+    value: &impl Serialize,
+) -> std::io::Result<()> {
+    let mut text = serde_json::to_string(value).unwrap_or_else(|_| "{}".to_string());
+    text.push('\n');
+    writer.write_all(text.as_bytes()).await
+}
+
+/// Aggregate + per-source line/error counters, for the `getStats` method.
+fn dispatch_get_stats(state: &AppState) -> serde_json::Value {
+    let mut sources: Vec<serde_json::Value> = state
+        .sources
+        .iter()
+        .map(|(id, stats)| {
+            serde_json::json!({
+                "source_id": id,
+                "label": stats.label,
+                "total_lines": stats.total_lines.load(Ordering::Relaxed),
+                "total_errors": stats.total_errors.load(Ordering::Relaxed),
+            })
+        })
+        .collect();
+    sources.sort_by(|a, b| a["source_id"].as_str().cmp(&b["source_id"].as_str()));
+
+    let uptime_secs = state.start_time.elapsed().as_secs();
+    let total_lines = state.total_lines();
+    let rate = if uptime_secs > 0 {
+        total_lines / uptime_secs
+    } else {
+        0
+    };
+
+    serde_json::json!({
+        "uptime_secs": uptime_secs,
+        "total_lines": total_lines,
+        "total_errors": state.total_errors(),
+        "rate": rate,
+        "sources": sources,
+    })
+}
+
+/// The bounded ring of recent alerts, oldest first, for `getRecentAlerts`.
+fn dispatch_get_recent_alerts(state: &AppState) -> serde_json::Value {
+    serde_json::json!(state.recent_alerts())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::AppState;
+
+    #[test]
+    fn dispatch_get_stats_reports_totals_and_sources() {
+        let state = AppState::new(None);
+        state.increment_lines(crate::state::DEFAULT_SOURCE_ID);
+        state.record_error(crate::state::DEFAULT_SOURCE_ID, "boom".to_string());
+
+        let stats = dispatch_get_stats(&state);
+        assert_eq!(stats["total_lines"], 1);
+        assert_eq!(stats["total_errors"], 1);
+        assert_eq!(stats["sources"][0]["source_id"], crate::state::DEFAULT_SOURCE_ID);
+    }
+
+    #[test]
+    fn dispatch_get_recent_alerts_returns_recorded_events() {
+        let state = AppState::new(None);
+        state.record_error(crate::state::DEFAULT_SOURCE_ID, "disk full".to_string());
+
+        let alerts = dispatch_get_recent_alerts(&state);
+        assert_eq!(alerts[0]["message"], "disk full");
+    }
+
+    /// Drives `handle_connection` over an in-memory duplex pipe, covering the
+    /// request/response dispatch as well as the `subscribe` push-stream half
+    /// of the `tokio::select!` loop, which the unit tests above don't touch.
+    #[tokio::test]
+    async fn handle_connection_dispatches_and_streams_subscribed_alerts() {
+        let state = Arc::new(AppState::new(None));
+        let (client, server) = tokio::io::duplex(4096);
+        tokio::spawn(handle_connection(server, state.clone()));
+
+        let (read_half, mut write_half) = tokio::io::split(client);
+        let mut lines = BufReader::new(read_half).lines();
+
+        write_half
+            .write_all(b"{\"id\": 1, \"method\": \"getStats\"}\n")
+            .await
+            .unwrap();
+        let response: serde_json::Value =
+            serde_json::from_str(&lines.next_line().await.unwrap().unwrap()).unwrap();
+        assert_eq!(response["id"], 1);
+        assert_eq!(response["result"]["total_lines"], 0);
+
+        write_half
+            .write_all(b"{\"id\": 2, \"method\": \"subscribe\"}\n")
+            .await
+            .unwrap();
+        let response: serde_json::Value =
+            serde_json::from_str(&lines.next_line().await.unwrap().unwrap()).unwrap();
+        assert_eq!(response["id"], 2);
+        assert_eq!(response["result"], "subscribed");
+
+        state.record_error(crate::state::DEFAULT_SOURCE_ID, "disk full".to_string());
+
+        let notification: serde_json::Value =
+            serde_json::from_str(&lines.next_line().await.unwrap().unwrap()).unwrap();
+        assert_eq!(notification["method"], "alert");
+        assert_eq!(notification["params"]["message"], "disk full");
+    }
+}
@@ -1,58 +1,553 @@
+use crate::config::SecurityConfig;
+use crossbeam::queue::ArrayQueue;
+use serde::Serialize;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, VecDeque};
+use std::hash::{Hash, Hasher};
 use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Mutex;
-use std::time::Instant;
+use std::time::{Duration, Instant};
+use tokio::sync::{Notify, broadcast};
 
+/// Tracks a single deduplicated alert: how many times it has fired since we
+/// last actually sent a webhook for it, and when it was last seen at all (so
+/// the resolve sweep can tell a dead incident from a quiet one).
+#[derive(Debug, Clone)]
+pub struct IncidentState {
+    pub last_message: String,
+    pub last_sent: Instant,
+    pub suppressed_count: u64,
+    pub last_seen: Instant,
+}
+
+/// What the caller should do after reporting a match for a given
+/// fingerprint: actually send a webhook, or stay quiet because we're still
+/// inside the incident's dedup window.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AlertDecision {
+    Send { suppressed_since_last: u64 },
+    Suppressed,
+}
+
+/// The source used when nothing in `AppConfig.sources` is configured,
+/// preserving the old single-file-hardcoded-to-`test.log` behavior.
+pub const DEFAULT_SOURCE_ID: &str = "test.log";
+
+/// How many recent alerts `AppState` keeps around for `rpc::serve`'s
+/// `getRecentAlerts` method. Older entries fall off as new ones arrive.
+const RECENT_ALERTS_CAPACITY: usize = 50;
+
+/// How many recent alert messages each `SourceStats` keeps for the TUI's
+/// "Recent Alerts" panel.
+const SOURCE_ALERT_RING_CAPACITY: usize = 20;
+
+/// How many pending webhook POSTs the dispatcher task will buffer. Once
+/// full, `send_webhook` evicts the oldest queued job to make room for the
+/// newest one rather than blocking the line-processing hot path — under a
+/// sustained storm, the most recent alert is more useful than one that's
+/// already stale by the time the dispatcher would get to it.
+const WEBHOOK_QUEUE_CAPACITY: usize = 256;
+
+/// One queued webhook POST, consumed by the single dispatcher task spawned
+/// in `main` over `AppState::next_webhook_job`.
+pub struct WebhookJob {
+    pub text: String,
+}
+
+/// One error-rule or JSON-error match that was recorded via `record_error`.
+/// Broadcast to JSON-RPC `subscribe` clients and snapshotted into
+/// `AppState::recent_alerts` for `getRecentAlerts`.
+#[derive(Debug, Clone, Serialize)]
+pub struct AlertEvent {
+    pub source_id: String,
+    pub message: String,
+}
+
+/// Per-file counters and recent-alert history. Replaces the old single
+/// global set of atomics so `AppState` can track an arbitrary number of
+/// tailed files independently.
 #[derive(Debug)]
-pub struct AppState {
+pub struct SourceStats {
+    pub label: String,
     pub total_lines: AtomicU64,
     pub total_errors: AtomicU64,
+    /// Lock-free bounded ring of the most recent alert messages for this
+    /// source, read by the TUI's "Recent Alerts" panel. Replaced the old
+    /// `Mutex<Option<String>>` last-error slot so the hot per-line path
+    /// never takes a lock.
+    recent_alerts: ArrayQueue<String>,
+}
+
+impl SourceStats {
+    pub fn new(label: String) -> Self {
+        Self {
+            label,
+            total_lines: AtomicU64::new(0),
+            total_errors: AtomicU64::new(0),
+            recent_alerts: ArrayQueue::new(SOURCE_ALERT_RING_CAPACITY),
+        }
+    }
+
+    /// Push a new alert onto the ring, evicting the oldest entry if full.
+    fn push_alert(&self, message: String) {
+        if self.recent_alerts.is_full() {
+            let _ = self.recent_alerts.pop();
+        }
+        let _ = self.recent_alerts.push(message);
+    }
+
+    /// Snapshot of the ring, oldest first, for display. `ArrayQueue` has no
+    /// non-destructive iterator, so this drains and refills it — fine at
+    /// this size and this read frequency (once per TUI frame, not per line).
+    pub fn recent_alerts_snapshot(&self) -> Vec<String> {
+        let mut items = Vec::new();
+        while let Some(item) = self.recent_alerts.pop() {
+            items.push(item);
+        }
+        for item in &items {
+            let _ = self.recent_alerts.push(item.clone());
+        }
+        items
+    }
+}
+
+#[derive(Debug)]
+pub struct AppState {
+    // Keyed by source id (the path the source was registered with).
+    pub sources: HashMap<String, SourceStats>,
     pub start_time: Instant,
-    pub last_error: Mutex<Option<String>>,
-    // New: For Sparklines
-    // REMOVED: error_history and last_history_update.
-    // The UI thread will track history by sampling total_errors.
-    // This removes the Mutex from the hot path.
     pub webhook_url: Option<String>,
-    pub last_webhook_sent: Mutex<Option<Instant>>,
+    // Per-fingerprint incident tracking, replacing the old global 10s gate.
+    pub incidents: Mutex<HashMap<u64, IncidentState>>,
+    pub dedup_window_secs: u64,
+    pub resolve_after_secs: u64,
+    // fail2ban-style IP offender tracking. None when `security` isn't
+    // configured, which keeps the whole subsystem a no-op.
+    pub ban_tracker: Option<BanTracker>,
+    // Fan-out for the JSON-RPC `subscribe` method. Sending is a no-op when
+    // nobody's listening, so this stays cheap when `rpc` isn't configured.
+    pub alert_tx: broadcast::Sender<AlertEvent>,
+    // Lock-free bounded ring backing the JSON-RPC `getRecentAlerts` method,
+    // so the per-line hot path never takes a mutex here either (mirrors
+    // `SourceStats::recent_alerts`).
+    recent_alerts: ArrayQueue<AlertEvent>,
+    // Single long-lived client shared by every webhook POST, instead of
+    // `reqwest::Client::new()` per alert.
+    pub http_client: reqwest::Client,
+    // Lock-free bounded dispatch queue: `send_webhook` evicts the oldest job
+    // to make room when full, so a storm applies backpressure by dropping
+    // stale alerts instead of spawning unbounded tasks.
+    webhook_queue: ArrayQueue<WebhookJob>,
+    // Wakes the dispatcher task (spawned in `main` over `next_webhook_job`)
+    // when a job is pushed onto an empty queue.
+    webhook_notify: Notify,
 }
 
 impl AppState {
     pub fn new(webhook_url: Option<String>) -> Self {
+        Self::with_dedup_config(webhook_url, 10, 300)
+    }
+
+    pub fn with_dedup_config(
+        webhook_url: Option<String>,
+        dedup_window_secs: u64,
+        resolve_after_secs: u64,
+    ) -> Self {
+        Self::with_security(webhook_url, dedup_window_secs, resolve_after_secs, None)
+    }
+
+    pub fn with_security(
+        webhook_url: Option<String>,
+        dedup_window_secs: u64,
+        resolve_after_secs: u64,
+        security: Option<&SecurityConfig>,
+    ) -> Self {
+        Self::with_sources(
+            vec![(DEFAULT_SOURCE_ID.to_string(), "default".to_string())],
+            webhook_url,
+            dedup_window_secs,
+            resolve_after_secs,
+            security,
+        )
+    }
+
+    /// `sources` is a list of `(source_id, label)` pairs — `source_id` must
+    /// match whatever `line.source()` reports for that file once it's
+    /// registered with linemux.
+    pub fn with_sources(
+        sources: Vec<(String, String)>,
+        webhook_url: Option<String>,
+        dedup_window_secs: u64,
+        resolve_after_secs: u64,
+        security: Option<&SecurityConfig>,
+    ) -> Self {
+        let sources = sources
+            .into_iter()
+            .map(|(id, label)| (id, SourceStats::new(label)))
+            .collect();
+
+        let (alert_tx, _) = broadcast::channel(256);
+
         Self {
-            total_lines: AtomicU64::new(0),
-            total_errors: AtomicU64::new(0),
+            sources,
             start_time: Instant::now(),
-            last_error: Mutex::new(None),
             webhook_url,
-            last_webhook_sent: Mutex::new(None),
+            incidents: Mutex::new(HashMap::new()),
+            dedup_window_secs,
+            resolve_after_secs,
+            ban_tracker: security.map(BanTracker::new),
+            alert_tx,
+            recent_alerts: ArrayQueue::new(RECENT_ALERTS_CAPACITY),
+            http_client: reqwest::Client::new(),
+            webhook_queue: ArrayQueue::new(WEBHOOK_QUEUE_CAPACITY),
+            webhook_notify: Notify::new(),
+        }
+    }
+
+    /// Queue `text` as a webhook POST body for the single dispatcher task.
+    /// Non-blocking: if the queue is already full, the oldest queued job is
+    /// evicted to make room, so the line-processing hot path never stalls
+    /// and the newest (most relevant) alert always gets queued.
+    pub fn send_webhook(&self, text: String) {
+        if self.webhook_queue.is_full() {
+            let _ = self.webhook_queue.pop();
+        }
+        let _ = self.webhook_queue.push(WebhookJob { text });
+        self.webhook_notify.notify_one();
+    }
+
+    /// Await the next queued webhook job, for the single dispatcher task
+    /// spawned in `main`. Only one task should call this in a loop — calling
+    /// it from more than one place would split the queue's jobs between them
+    /// rather than giving each task every job.
+    pub async fn next_webhook_job(&self) -> WebhookJob {
+        loop {
+            if let Some(job) = self.webhook_queue.pop() {
+                return job;
+            }
+            self.webhook_notify.notified().await;
+        }
+    }
+
+    pub fn increment_lines(&self, source_id: &str) {
+        if let Some(stats) = self.sources.get(source_id) {
+            stats.total_lines.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub fn record_error(&self, source_id: &str, message: String) {
+        if let Some(stats) = self.sources.get(source_id) {
+            stats.total_errors.fetch_add(1, Ordering::Relaxed);
+            stats.push_alert(message.clone());
+
+            let event = AlertEvent {
+                source_id: source_id.to_string(),
+                message,
+            };
+
+            if self.recent_alerts.is_full() {
+                let _ = self.recent_alerts.pop();
+            }
+            let _ = self.recent_alerts.push(event.clone());
+
+            // No receivers (e.g. `rpc` isn't configured) just means this
+            // send errors out; that's fine, nobody's listening.
+            let _ = self.alert_tx.send(event);
+        }
+    }
+
+    /// Snapshot of the most recent alerts, oldest first, for the JSON-RPC
+    /// `getRecentAlerts` method. `ArrayQueue` has no non-destructive
+    /// iterator, so this drains and refills it — fine at this size and this
+    /// read frequency (once per RPC call, not per line).
+    pub fn recent_alerts(&self) -> Vec<AlertEvent> {
+        let mut items = Vec::new();
+        while let Some(item) = self.recent_alerts.pop() {
+            items.push(item);
         }
+        for item in &items {
+            let _ = self.recent_alerts.push(item.clone());
+        }
+        items
+    }
+
+    pub fn total_lines(&self) -> u64 {
+        self.sources
+            .values()
+            .map(|s| s.total_lines.load(Ordering::Relaxed))
+            .sum()
+    }
+
+    pub fn total_errors(&self) -> u64 {
+        self.sources
+            .values()
+            .map(|s| s.total_errors.load(Ordering::Relaxed))
+            .sum()
     }
 
-    pub fn should_send_webhook(&self) -> bool {
-        let mut last_sent = self.last_webhook_sent.lock().unwrap();
-        match *last_sent {
-            Some(instant) => {
-                if instant.elapsed() > std::time::Duration::from_secs(10) {
-                    *last_sent = Some(Instant::now());
-                    true
+    /// Record that `message` (already hashed into `fingerprint`) just matched,
+    /// and decide whether this occurrence should actually trigger a webhook
+    /// or be folded into an existing incident's suppressed count.
+    pub fn record_alert(&self, fingerprint: u64, message: &str) -> AlertDecision {
+        let mut incidents = self.incidents.lock().unwrap();
+        let now = Instant::now();
+
+        match incidents.get_mut(&fingerprint) {
+            Some(incident) => {
+                incident.last_seen = now;
+                incident.last_message = message.to_string();
+
+                if incident.last_sent.elapsed() > Duration::from_secs(self.dedup_window_secs) {
+                    let suppressed_since_last = incident.suppressed_count;
+                    incident.last_sent = now;
+                    incident.suppressed_count = 0;
+                    AlertDecision::Send {
+                        suppressed_since_last,
+                    }
                 } else {
-                    false
+                    incident.suppressed_count += 1;
+                    AlertDecision::Suppressed
                 }
             }
             None => {
-                *last_sent = Some(Instant::now());
+                incidents.insert(
+                    fingerprint,
+                    IncidentState {
+                        last_message: message.to_string(),
+                        last_sent: now,
+                        suppressed_count: 0,
+                        last_seen: now,
+                    },
+                );
+                AlertDecision::Send {
+                    suppressed_since_last: 0,
+                }
+            }
+        }
+    }
+
+    /// Drop incidents that haven't been seen in `resolve_after_secs` and
+    /// return their last known message, so the caller can emit a "Resolved"
+    /// webhook for each.
+    pub fn sweep_resolved_incidents(&self) -> Vec<(u64, String)> {
+        let mut incidents = self.incidents.lock().unwrap();
+        let resolve_after = Duration::from_secs(self.resolve_after_secs);
+        let mut resolved = Vec::new();
+
+        incidents.retain(|fingerprint, incident| {
+            if incident.last_seen.elapsed() > resolve_after {
+                resolved.push((*fingerprint, incident.last_message.clone()));
+                false
+            } else {
                 true
             }
+        });
+
+        resolved
+    }
+}
+
+/// Normalize an alert message into a fingerprint so near-duplicate errors
+/// (differing only by a counter, request id, or similar) collapse onto the
+/// same incident. Runs of digits and hex/UUID-looking tokens are replaced
+/// with a placeholder before hashing.
+pub fn fingerprint_message(message: &str) -> u64 {
+    let normalized = normalize_message(message);
+    let mut hasher = DefaultHasher::new();
+    normalized.hash(&mut hasher);
+    hasher.finish()
+}
+
+fn normalize_message(message: &str) -> String {
+    let lower = message.to_lowercase();
+    let mut normalized = String::with_capacity(lower.len());
+    let mut chars = lower.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c.is_ascii_hexdigit() && chars.peek().is_some_and(|n| n.is_ascii_hexdigit()) {
+            // Tentatively consume a run of hex digits (covers plain numbers
+            // and hex/UUID-style tokens alike), but only collapse it if a
+            // digit actually showed up somewhere in the run — otherwise an
+            // all-`a`-`f` English word (cafe, added, face, decade, ...) would
+            // get nuked the same as a real hex/UUID token and unrelated
+            // alerts would collide.
+            let mut run = String::new();
+            run.push(c);
+            while chars.peek().is_some_and(|n| n.is_ascii_hexdigit() || *n == '-') {
+                run.push(chars.next().unwrap());
+            }
+            if run.chars().any(|ch| ch.is_ascii_digit()) {
+                normalized.push('#');
+            } else {
+                normalized.push_str(&run);
+            }
+        } else if c.is_ascii_digit() {
+            while chars.peek().is_some_and(|n| n.is_ascii_digit()) {
+                chars.next();
+            }
+            normalized.push('#');
+        } else {
+            normalized.push(c);
+        }
+    }
+
+    normalized
+}
+
+/// An IP currently serving out a ban, and when that ban expires.
+#[derive(Debug, Clone)]
+pub struct BannedIp {
+    pub banned_at: Instant,
+    pub expires_at: Instant,
+}
+
+/// fail2ban-style repeat-offender tracker: a per-IP sliding window of
+/// failures that promotes an IP to `banned` once it crosses `ban_threshold`
+/// within `ban_window_secs`, and expires it after `ban_duration_secs`.
+#[derive(Debug)]
+pub struct BanTracker {
+    ban_threshold: u64,
+    ban_window_secs: u64,
+    ban_duration_secs: u64,
+    pub blocklist_path: String,
+    pub ban_command: Option<String>,
+    pub unban_command: Option<String>,
+    failures: Mutex<HashMap<String, VecDeque<Instant>>>,
+    banned: Mutex<HashMap<String, BannedIp>>,
+}
+
+impl BanTracker {
+    pub fn new(config: &SecurityConfig) -> Self {
+        Self {
+            ban_threshold: config.ban_threshold,
+            ban_window_secs: config.ban_window_secs,
+            ban_duration_secs: config.ban_duration_secs,
+            blocklist_path: config.blocklist_path.clone(),
+            ban_command: config.ban_command.clone(),
+            unban_command: config.unban_command.clone(),
+            failures: Mutex::new(HashMap::new()),
+            banned: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Record a failure for `ip`. Returns `true` if this failure just tipped
+    /// the IP over `ban_threshold`, meaning the caller should actually apply
+    /// the ban (write the blocklist, run the ban command).
+    pub fn record_failure(&self, ip: &str) -> bool {
+        if self.banned.lock().unwrap().contains_key(ip) {
+            return false;
+        }
+
+        let mut failures = self.failures.lock().unwrap();
+        let now = Instant::now();
+        let cutoff = Duration::from_secs(self.ban_window_secs);
+        let window = failures.entry(ip.to_string()).or_default();
+
+        window.push_back(now);
+        while let Some(&front) = window.front() {
+            if now.duration_since(front) > cutoff {
+                window.pop_front();
+            } else {
+                break;
+            }
+        }
+
+        if window.len() as u64 >= self.ban_threshold {
+            window.clear();
+            self.banned.lock().unwrap().insert(
+                ip.to_string(),
+                BannedIp {
+                    banned_at: now,
+                    expires_at: now + Duration::from_secs(self.ban_duration_secs),
+                },
+            );
+            true
+        } else {
+            false
         }
     }
 
-    pub fn increment_lines(&self) {
-        self.total_lines.fetch_add(1, Ordering::Relaxed);
+    /// Drop bans whose expiry has passed from Sentinel's own bookkeeping,
+    /// returning the IPs that were just unbanned. This only clears internal
+    /// state — the caller must feed the result through
+    /// `parser::apply_unbans` to actually reverse the ban (blocklist file,
+    /// `unban_command`).
+    pub fn sweep_expired(&self) -> Vec<String> {
+        let mut banned = self.banned.lock().unwrap();
+        let now = Instant::now();
+        let mut expired = Vec::new();
+
+        banned.retain(|ip, b| {
+            if now >= b.expires_at {
+                expired.push(ip.clone());
+                false
+            } else {
+                true
+            }
+        });
+
+        expired
     }
 
-    pub fn record_error(&self, message: String) {
-        self.total_errors.fetch_add(1, Ordering::Relaxed);
-        let mut last = self.last_error.lock().unwrap();
-        *last = Some(message);
+    /// Currently banned IPs, for display in the TUI.
+    pub fn banned_ips(&self) -> Vec<String> {
+        self.banned.lock().unwrap().keys().cloned().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fingerprint_collapses_numeric_variants() {
+        let a = fingerprint_message("Critical usage 501");
+        let b = fingerprint_message("Critical usage 502");
+        assert_eq!(a, b);
+    }
+
+    #[test]
+    fn fingerprint_distinguishes_different_messages() {
+        let a = fingerprint_message("Critical usage 501");
+        let b = fingerprint_message("Disk full");
+        assert_ne!(a, b);
+    }
+
+    #[test]
+    fn record_alert_suppresses_within_window() {
+        let state = AppState::with_dedup_config(None, 10, 300);
+        let fp = fingerprint_message("Critical usage 501");
+
+        let first = state.record_alert(fp, "Critical usage 501");
+        assert_eq!(
+            first,
+            AlertDecision::Send {
+                suppressed_since_last: 0
+            }
+        );
+
+        let second = state.record_alert(fp, "Critical usage 502");
+        assert_eq!(second, AlertDecision::Suppressed);
+    }
+
+    #[test]
+    fn ban_tracker_bans_after_threshold() {
+        let config = SecurityConfig {
+            ban_threshold: 3,
+            ban_window_secs: 60,
+            ban_duration_secs: 300,
+            blocklist_path: "blocklist.txt".to_string(),
+            ban_command: None,
+            unban_command: None,
+        };
+        let tracker = BanTracker::new(&config);
+
+        assert!(!tracker.record_failure("1.2.3.4"));
+        assert!(!tracker.record_failure("1.2.3.4"));
+        assert!(tracker.record_failure("1.2.3.4"));
+        assert_eq!(tracker.banned_ips(), vec!["1.2.3.4".to_string()]);
+
+        // Already banned, so further failures don't re-trigger.
+        assert!(!tracker.record_failure("1.2.3.4"));
     }
 }